@@ -1,6 +1,7 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
 
 use serde::{Deserialize, Serialize};
 use serde_json::{from_reader, to_writer, to_writer_pretty};
@@ -9,41 +10,242 @@ use unicode_segmentation::UnicodeSegmentation;
 const DEFAULT_FILE_PATH: &str = "model.json";
 const INITIAL_RATING: f32 = 0.5;
 const SPAM_PROB_THRESHOLD: f32 = 0.8;
+const SPAM_LABEL: &str = "spam";
+const HAM_LABEL: &str = "ham";
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-struct Counter {
-    ham: u32,
-    spam: u32,
+/// The strategy used to combine per-word ratings into a single score.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScoringMode {
+    /// Combine word ratings with the naive Bayes product formula
+    /// (`Classifier::score`'s historical behavior).
+    #[default]
+    Naive,
+    /// Combine word ratings with Robinson's Fisher chi-squared method
+    /// (`Classifier::score_chi2`), which is more robust to message length
+    /// and correlated tokens than the naive product.
+    Chi2,
 }
 
-/// A bayesian spam classifier.
-#[derive(Default, Debug, Deserialize, Serialize)]
+/// Configures how messages are split into tokens for training and scoring.
+///
+/// The default configuration reproduces the historical behavior: messages
+/// are split on Unicode word boundaries, case is preserved, no stop words
+/// are removed, and only single-word tokens (unigrams) are produced.
+#[derive(Debug, Clone)]
+pub struct Tokenizer {
+    lowercase: bool,
+    stop_words: HashSet<String>,
+    ngram_range: (usize, usize),
+}
+
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Self {
+            lowercase: false,
+            stop_words: HashSet::new(),
+            ngram_range: (1, 1),
+        }
+    }
+}
+
+impl Tokenizer {
+    /// Build a new tokenizer with the default, historical configuration.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Fold every token to lowercase before counting it, so e.g. `FREE` and
+    /// `free` are treated as the same token.
+    pub fn set_lowercase(&mut self, enabled: bool) {
+        self.lowercase = enabled;
+    }
+
+    /// Discard tokens found in `stop_words` instead of counting them.
+    pub fn set_stop_words(&mut self, stop_words: HashSet<String>) {
+        self.stop_words = stop_words;
+    }
+
+    /// Emit word n-grams from `min_n` to `max_n` words long (inclusive)
+    /// instead of single words, so phrases can be captured as tokens.
+    pub fn set_ngram_range(&mut self, min_n: usize, max_n: usize) {
+        let min_n = min_n.max(1);
+        self.ngram_range = (min_n, max_n.max(min_n));
+    }
+
+    /// Split `msg` into a list of tokens, according to this configuration.
+    fn tokenize(&self, msg: &str) -> Vec<String> {
+        // Stop words are matched against tokens *after* lowercasing, so they
+        // need to be folded to the same case here too, regardless of the
+        // case they were supplied in to `set_stop_words`.
+        let stop_words: HashSet<String> = if self.lowercase {
+            self.stop_words.iter().map(|word| word.to_lowercase()).collect()
+        } else {
+            self.stop_words.clone()
+        };
+
+        let words: Vec<String> = msg
+            .unicode_words()
+            .map(|word| {
+                if self.lowercase {
+                    word.to_lowercase()
+                } else {
+                    word.to_string()
+                }
+            })
+            .filter(|word| !stop_words.contains(word))
+            .collect();
+
+        let (min_n, max_n) = self.ngram_range;
+        let mut tokens = Vec::new();
+        for n in min_n..=max_n {
+            if n == 0 || n > words.len() {
+                continue;
+            }
+            for window in words.windows(n) {
+                tokens.push(window.join(" "));
+            }
+        }
+        tokens
+    }
+}
+
+/// A three-way verdict produced by `Classifier::classify_verdict`, which
+/// leaves borderline messages "unsure" rather than forcing them into spam or
+/// ham.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Ham,
+    Unsure,
+    Spam,
+}
+
+/// The outcome of evaluating a classifier's spam/ham predictions against a
+/// held-out labeled test set, as produced by `Classifier::evaluate`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Evaluation {
+    pub true_positives: u32,
+    pub false_positives: u32,
+    pub true_negatives: u32,
+    pub false_negatives: u32,
+}
+
+impl Evaluation {
+    /// The proportion of predictions that were correct.
+    pub fn accuracy(&self) -> f32 {
+        let total = self.true_positives + self.false_positives + self.true_negatives + self.false_negatives;
+        if total == 0 {
+            return 0.0;
+        }
+        (self.true_positives + self.true_negatives) as f32 / total as f32
+    }
+
+    /// Of the messages predicted spam, the proportion that actually were.
+    pub fn precision(&self) -> f32 {
+        let predicted_positive = self.true_positives + self.false_positives;
+        if predicted_positive == 0 {
+            return 0.0;
+        }
+        self.true_positives as f32 / predicted_positive as f32
+    }
+
+    /// Of the messages that actually were spam, the proportion caught.
+    pub fn recall(&self) -> f32 {
+        let actual_positive = self.true_positives + self.false_negatives;
+        if actual_positive == 0 {
+            return 0.0;
+        }
+        self.true_positives as f32 / actual_positive as f32
+    }
+
+    /// The harmonic mean of `precision` and `recall`.
+    pub fn f1(&self) -> f32 {
+        let precision = self.precision();
+        let recall = self.recall();
+        if precision + recall == 0.0 {
+            return 0.0;
+        }
+        2.0 * precision * recall / (precision + recall)
+    }
+}
+
+/// A bayesian classifier, generalized to an arbitrary set of categories.
+///
+/// Internally, each token is associated with the number of times it was seen
+/// under each category, so the classifier is no longer limited to the
+/// `ham`/`spam` case: any number of labels can be trained and scored.
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(from = "ClassifierSerialized")]
 pub struct Classifier {
-    token_table: HashMap<String, Counter>,
+    token_table: HashMap<String, HashMap<String, u32>>,
     #[serde(skip)]
-    spam_total_count: u32,
+    category_totals: HashMap<String, u32>,
     #[serde(skip)]
-    ham_total_count: u32,
+    scoring_mode: ScoringMode,
+    // Whether `score_naive` should keep only the 10 highest and 10 lowest
+    // word ratings on long messages. Enabled by default for backward
+    // compatibility; has no effect on `score_chi2`, which already scales
+    // with message length.
+    #[serde(skip)]
+    truncate_long_messages: bool,
+    // Add-k (Lidstone) smoothing constant used by `rate_words`.
+    #[serde(skip)]
+    smoothing_k: f32,
+    // Robinson's `s`: the weight given to the assumed prior probability
+    // before any evidence has been seen for a word.
+    #[serde(skip)]
+    prior_strength: f32,
+    // Robinson's `x`: the assumed probability for a word with no evidence.
+    #[serde(skip)]
+    prior_probability: f32,
+    // Below this score, `classify_verdict` returns `Verdict::Ham`.
+    #[serde(skip)]
+    ham_cutoff: f32,
+    // Above this score, `classify_verdict` returns `Verdict::Spam`.
+    #[serde(skip)]
+    spam_cutoff: f32,
+    // Splits messages into tokens for every train/score path.
+    #[serde(skip)]
+    tokenizer: Tokenizer,
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Self {
+            token_table: HashMap::new(),
+            category_totals: HashMap::new(),
+            scoring_mode: ScoringMode::default(),
+            truncate_long_messages: true,
+            smoothing_k: 0.05,
+            prior_strength: 0.5,
+            prior_probability: INITIAL_RATING,
+            ham_cutoff: 0.2,
+            spam_cutoff: SPAM_PROB_THRESHOLD,
+            tokenizer: Tokenizer::default(),
+        }
+    }
 }
 
 /// The classifier model as it is serialized to disk.
 ///
-/// Does not include the `spam_total_count` and `ham_total_count` fields which
-/// can be recomputed from `token_table`.
+/// Does not include the `category_totals` field, which can be recomputed
+/// from `token_table`.
 #[derive(Deserialize, Serialize)]
 struct ClassifierSerialized {
-    token_table: HashMap<String, Counter>,
+    token_table: HashMap<String, HashMap<String, u32>>,
 }
 
 impl std::convert::From<ClassifierSerialized> for Classifier {
     fn from(c: ClassifierSerialized) -> Self {
-        let spam_total_count = c.token_table.values().map(|x| x.spam).sum();
-        let ham_total_count = c.token_table.values().map(|x| x.ham).sum();
+        let mut category_totals = HashMap::new();
+        for counts in c.token_table.values() {
+            for (label, count) in counts {
+                *category_totals.entry(label.clone()).or_insert(0) += count;
+            }
+        }
         Self {
             token_table: c.token_table,
-            spam_total_count,
-            ham_total_count,
+            category_totals,
+            ..Default::default()
         }
     }
 }
@@ -54,132 +256,477 @@ impl Classifier {
         Default::default()
     }
 
-    /// Build a new classifier with a pre-trained model loaded from `file`.
-    pub fn new_from_pre_trained(file: &mut File) -> Result<Self, io::Error> {
-        let pre_trained_model = from_reader(file)?;
+    /// Build a new classifier with a pre-trained model loaded as JSON from
+    /// `reader`, which can be a `File`, an in-memory buffer, a compressed
+    /// stream, or anything else that implements `Read`.
+    pub fn new_from_pre_trained(reader: impl Read) -> Result<Self, io::Error> {
+        let pre_trained_model = from_reader(reader)?;
         Ok(pre_trained_model)
     }
 
-    /// Save the classifier to `file` as JSON.
+    /// Save the classifier as JSON to `writer`, which can be a `File`, an
+    /// in-memory buffer, a compressed stream, or anything else that
+    /// implements `Write`.
     /// The JSON will be pretty printed if `pretty` is `true`.
-    pub fn save(&self, file: &mut File, pretty: bool) -> Result<(), io::Error> {
+    pub fn save(&self, writer: impl Write, pretty: bool) -> Result<(), io::Error> {
         if pretty {
-            to_writer_pretty(file, &self)?;
+            to_writer_pretty(writer, &self)?;
         } else {
-            to_writer(file, &self)?;
+            to_writer(writer, &self)?;
         }
         Ok(())
     }
 
-    /// Split `msg` into a list of words.
-    fn load_word_list(msg: &str) -> Vec<String> {
-        let word_list = msg.unicode_words().collect::<Vec<&str>>();
-        word_list.iter().map(|word| word.to_string()).collect()
+    /// Build a new classifier with a pre-trained model loaded from the
+    /// compact `bincode` representation produced by `save_bincode`.
+    #[cfg(feature = "bincode")]
+    pub fn new_from_pre_trained_bincode(reader: impl Read) -> Result<Self, io::Error> {
+        bincode::deserialize_from(reader).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Save the classifier to `writer` using the compact `bincode` format,
+    /// which is smaller and faster to (de)serialize than JSON for large
+    /// vocabularies.
+    #[cfg(feature = "bincode")]
+    pub fn save_bincode(&self, writer: impl Write) -> Result<(), io::Error> {
+        bincode::serialize_into(writer, &self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Set the tokenizer used by every train/score path.
+    pub fn set_tokenizer(&mut self, tokenizer: Tokenizer) {
+        self.tokenizer = tokenizer;
+    }
+
+    /// Train the classifier with a `msg` belonging to `label`.
+    ///
+    /// `label` can be any category name; it does not need to be `ham` or
+    /// `spam`, and new labels can be introduced simply by training with them.
+    pub fn train(&mut self, label: &str, msg: &str) {
+        let word_list = self.tokenizer.tokenize(msg);
+        *self.category_totals.entry(label.to_string()).or_insert(0) += word_list.len() as u32;
+
+        for word in word_list {
+            let counts = self.token_table.entry(word).or_default();
+            *counts.entry(label.to_string()).or_insert(0) += 1;
+        }
     }
 
     /// Train the classifier with a spam `msg`.
     pub fn train_spam(&mut self, msg: &str) {
-        for word in Self::load_word_list(msg) {
-            let counter = self.token_table.entry(word).or_default();
-            counter.spam += 1;
-            self.spam_total_count += 1;
-        }
+        self.train(SPAM_LABEL, msg);
     }
 
     /// Train the classifier with a ham `msg`.
     pub fn train_ham(&mut self, msg: &str) {
-        for word in Self::load_word_list(msg) {
-            let counter = self.token_table.entry(word).or_default();
-            counter.ham += 1;
-            self.ham_total_count += 1;
+        self.train(HAM_LABEL, msg);
+    }
+
+    /// Reverse a previous `train` call, decrementing the counters for `msg`
+    /// under `label`.
+    ///
+    /// Counts saturate at zero rather than going negative, and a token is
+    /// dropped from the model entirely once its count under every category
+    /// reaches zero, so repeated train/untrain cycles don't leave the
+    /// `token_table` growing unbounded. `label` itself is dropped from
+    /// `category_totals` once its total reaches zero, so a fully-untrained
+    /// category stops showing up as a live class in `scores`/`classify`.
+    /// This is what powers "this was not spam"-style feedback loops without
+    /// requiring a full retrain.
+    pub fn untrain(&mut self, label: &str, msg: &str) {
+        let mut removed = 0;
+
+        for word in self.tokenizer.tokenize(msg) {
+            if let Some(counts) = self.token_table.get_mut(&word) {
+                if let Some(count) = counts.get_mut(label) {
+                    if *count > 0 {
+                        *count -= 1;
+                        removed += 1;
+                    }
+                    if *count == 0 {
+                        counts.remove(label);
+                    }
+                }
+                if counts.is_empty() {
+                    self.token_table.remove(&word);
+                }
+            }
+        }
+
+        if let Some(total) = self.category_totals.get_mut(label) {
+            *total = total.saturating_sub(removed);
+            if *total == 0 {
+                self.category_totals.remove(label);
+            }
         }
     }
 
-    /// Return the total number of spam in token table.
-    fn spam_total_count(&self) -> u32 {
-        self.spam_total_count
+    /// Reverse a previous `train_spam` call for `msg`.
+    pub fn untrain_spam(&mut self, msg: &str) {
+        self.untrain(SPAM_LABEL, msg);
     }
 
-    /// Return the total number of ham in token table.
-    fn ham_total_count(&self) -> u32 {
-        self.ham_total_count
+    /// Reverse a previous `train_ham` call for `msg`.
+    pub fn untrain_ham(&mut self, msg: &str) {
+        self.untrain(HAM_LABEL, msg);
     }
 
-    /// Compute the probability of each word of `msg` to be part of a spam.
-    fn rate_words(&self, msg: &str) -> Vec<f32> {
-        Self::load_word_list(msg)
+    /// Train the classifier with every file found directly under `dir`,
+    /// treating each file's contents as one `msg` belonging to `label`.
+    ///
+    /// This lets a classifier be trained straight from a labeled corpus
+    /// directory (e.g. one directory per category of the Enron or SMS-Spam
+    /// datasets) instead of hand-feeding individual strings.
+    pub fn train_from_dir<P: AsRef<Path>>(&mut self, dir: P, label: &str) -> Result<(), io::Error> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                let msg = fs::read_to_string(path)?;
+                self.train(label, &msg);
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the total number of words trained under `label`.
+    fn category_total(&self, label: &str) -> u32 {
+        *self.category_totals.get(label).unwrap_or(&0)
+    }
+
+    /// Configure the smoothing applied by `rate_words`.
+    ///
+    /// `k` is the add-k (Lidstone) smoothing constant applied to every word
+    /// count. `prior_strength` and `prior_probability` are Robinson's `s`
+    /// and `x`: the weight given to the assumed `prior_probability` before
+    /// any evidence has been seen for a word. Higher `prior_strength` values
+    /// make the classifier trust rare words less and the prior more.
+    pub fn set_smoothing(&mut self, k: f32, prior_strength: f32, prior_probability: f32) {
+        self.smoothing_k = k;
+        self.prior_strength = prior_strength;
+        self.prior_probability = prior_probability;
+    }
+
+    /// Compute the probability of each word of `msg` to belong to `label`,
+    /// relative to every other category the classifier knows about.
+    ///
+    /// Counts are smoothed with add-k (Lidstone) smoothing, then blended
+    /// with the configured prior so that the returned probability moves
+    /// smoothly from `prior_probability` towards the observed rate as more
+    /// evidence accumulates for a word, rather than snapping to a hardcoded
+    /// extreme the moment a word is seen in only one category.
+    fn rate_words(&self, label: &str, msg: &str) -> Vec<f32> {
+        let label_total = self.category_total(label);
+        let other_total: u32 = self
+            .category_totals
+            .iter()
+            .filter(|(other_label, _)| other_label.as_str() != label)
+            .map(|(_, count)| count)
+            .sum();
+        let vocab_size = self.token_table.len() as f32;
+
+        self.tokenizer
+            .tokenize(msg)
             .into_iter()
             .map(|word| {
-                // If word was previously added in the model
-                if let Some(counter) = self.token_table.get(&word) {
-                    // If the word has only been part of spam messages,
-                    // assign it a probability of 0.99 to be part of a spam
-                    if counter.spam > 0 && counter.ham == 0 {
-                        return 0.99;
-                    // If the word has only been part of ham messages,
-                    // assign it a probability of 0.01 to be part of a spam
-                    } else if counter.spam == 0 && counter.ham > 0 {
-                        return 0.01;
-                    // If the word has been part of both spam and ham messages,
-                    // calculate the probability to be part of a spam
-                    } else if self.spam_total_count() > 0 && self.ham_total_count() > 0 {
-                        let ham_prob = (counter.ham as f32) / (self.ham_total_count() as f32);
-                        let spam_prob = (counter.spam as f32) / (self.spam_total_count() as f32);
-                        return (spam_prob / (ham_prob + spam_prob)).max(0.01);
+                let (label_count, other_count) = match self.token_table.get(&word) {
+                    Some(counts) => {
+                        let label_count = *counts.get(label).unwrap_or(&0);
+                        let other_count: u32 = counts
+                            .iter()
+                            .filter(|(other_label, _)| other_label.as_str() != label)
+                            .map(|(_, count)| count)
+                            .sum();
+                        (label_count, other_count)
                     }
-                }
-                // If word was never added to the model,
-                // assign it an initial probability to be part of a spam
-                INITIAL_RATING
+                    None => (0, 0),
+                };
+
+                // Add-k smoothed estimate of how often `word` appears under
+                // `label` versus every other category
+                let label_denom = label_total as f32 + self.smoothing_k * vocab_size;
+                let other_denom = other_total as f32 + self.smoothing_k * vocab_size;
+                let label_rate = if label_denom > 0.0 {
+                    (label_count as f32 + self.smoothing_k) / label_denom
+                } else {
+                    0.0
+                };
+                let other_rate = if other_denom > 0.0 {
+                    (other_count as f32 + self.smoothing_k) / other_denom
+                } else {
+                    0.0
+                };
+                let raw_probability = if label_rate + other_rate > 0.0 {
+                    label_rate / (label_rate + other_rate)
+                } else {
+                    self.prior_probability
+                };
+
+                // Blend the raw estimate with the prior, trusting it more as
+                // evidence `n` accumulates for this particular word
+                let n = (label_count + other_count) as f32;
+                ((self.prior_strength * self.prior_probability + n * raw_probability)
+                    / (self.prior_strength + n))
+                    .clamp(0.01, 0.99)
             })
             .collect()
     }
 
-    /// Compute the spam score of `msg`.
+    /// Set the strategy used by `score` to combine per-word ratings.
+    pub fn set_scoring_mode(&mut self, mode: ScoringMode) {
+        self.scoring_mode = mode;
+    }
+
+    /// Control whether `score_naive` keeps only the 10 highest and 10 lowest
+    /// word ratings on messages longer than 20 words. Disabling this lets
+    /// `score` take every word of the message into account, which the
+    /// log-space combiner now makes numerically safe to do.
+    pub fn set_truncate_long_messages(&mut self, enabled: bool) {
+        self.truncate_long_messages = enabled;
+    }
+
+    /// Compute the spam score of `msg`, using the classifier's configured
+    /// `ScoringMode`.
     /// The higher the score, the stronger the liklihood that `msg` is a spam is.
     pub fn score(&self, msg: &str) -> f32 {
-        // Compute the probability of each word to be part of a spam
-        let ratings = self.rate_words(msg);
+        match self.scoring_mode {
+            ScoringMode::Naive => self.score_naive(msg),
+            ScoringMode::Chi2 => self.score_chi2(msg),
+        }
+    }
 
-        let ratings = match ratings.len() {
+    /// Compute the spam score of `msg` with the naive Bayes product formula.
+    /// The higher the score, the stronger the liklihood that `msg` is a spam is.
+    fn score_naive(&self, msg: &str) -> f32 {
+        // Compute the probability of each word to be part of a spam
+        let ratings = self.rate_words(SPAM_LABEL, msg);
+        if ratings.is_empty() {
             // If there are no ratings, return a score of 0
-            0 => return 0.0,
-            // If there are more than 20 ratings, keep only the 10 first
-            // and 10 last ratings to calculate a score
-            x if x > 20 => {
-                let length = ratings.len();
-                let mut ratings = ratings;
-                ratings.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
-                [&ratings[..10], &ratings[length - 10..]].concat()
-            }
-            // In all other cases, keep ratings to calculate a score
-            _ => ratings,
+            return 0.0;
+        }
+
+        // If enabled, and there are more than 20 ratings, keep only the 10
+        // first and 10 last ratings to calculate a score
+        let ratings = if self.truncate_long_messages && ratings.len() > 20 {
+            let length = ratings.len();
+            let mut ratings = ratings;
+            ratings.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+            [&ratings[..10], &ratings[length - 10..]].concat()
+        } else {
+            ratings
         };
 
-        // Combine individual ratings
-        let product: f32 = ratings.iter().product();
-        let alt_product: f32 = ratings.iter().map(|x| 1.0 - x).product();
+        // Combine individual ratings in log-space to avoid the underflow a
+        // direct product suffers on long, untruncated messages
+        let log_product: f32 = ratings.iter().map(|p| p.ln()).sum();
+        let log_alt_product: f32 = ratings.iter().map(|p| (1.0 - p).ln()).sum();
+
+        // Recover the final probability with a numerically stable
+        // log-sum-exp: subtract the max log before exponentiating
+        let max_log = log_product.max(log_alt_product);
+        let product = (log_product - max_log).exp();
+        let alt_product = (log_alt_product - max_log).exp();
+
         product / (product + alt_product)
     }
 
-    /// Identify whether `msg` is a spam or not.
+    /// Compute the spam score of `msg` with Robinson's Fisher chi-squared
+    /// combiner, following the spambayes `chi2` method.
+    ///
+    /// Unlike the naive product that [`Classifier::score`] uses in
+    /// `ScoringMode::Naive`, this combines evidence
+    /// from every word rating via Fisher's method, which stays numerically
+    /// stable and meaningful regardless of message length.
+    pub fn score_chi2(&self, msg: &str) -> f32 {
+        let ratings = self.rate_words(SPAM_LABEL, msg);
+        let n = ratings.len();
+        if n == 0 {
+            return 0.0;
+        }
+
+        let sum_ln_p: f32 = ratings.iter().map(|p| p.ln()).sum();
+        let sum_ln_1_minus_p: f32 = ratings.iter().map(|p| (1.0 - p).ln()).sum();
+
+        // S is derived from the ham evidence (ln p_i), H from the spam
+        // evidence (ln (1 - p_i)); see the comment below for how that plays
+        // out in the final indicator.
+        let s = 1.0 - chi2_survival(-2.0 * sum_ln_p, n);
+        let h = 1.0 - chi2_survival(-2.0 * sum_ln_1_minus_p, n);
+
+        // `h` climbs towards 1 when the words carry strong spam evidence
+        // (every p_i close to 1 drives ln(1 - p_i) sharply negative), and
+        // `s` does the same for ham evidence, so H vs S gives scores near 1
+        // for spam and near 0 for ham.
+        ((h - s + 1.0) / 2.0).clamp(0.0, 1.0)
+    }
+
+    /// Identify whether `msg` is a spam or not, using the same `spam_cutoff`
+    /// configured via `set_verdict_cutoffs` rather than a hardcoded
+    /// threshold, so it agrees with `classify_verdict` on the same message.
     pub fn identify(&self, msg: &str) -> bool {
-        self.score(msg) > SPAM_PROB_THRESHOLD
+        self.score(msg) > self.spam_cutoff
+    }
+
+    /// Set the cutoffs used by `classify_verdict` and `identify` (and so
+    /// `evaluate`, which calls `identify`). Scores below `ham_cutoff` are
+    /// `Verdict::Ham`, scores above `spam_cutoff` are `Verdict::Spam` and
+    /// count as spam for `identify`, and anything in between is
+    /// `Verdict::Unsure`.
+    pub fn set_verdict_cutoffs(&mut self, ham_cutoff: f32, spam_cutoff: f32) {
+        self.ham_cutoff = ham_cutoff;
+        self.spam_cutoff = spam_cutoff;
+    }
+
+    /// Classify `msg` into `Ham`, `Unsure` or `Spam`, rather than forcing a
+    /// borderline score into one bucket or the other. Messages landing in
+    /// `Unsure` are good candidates for manual review instead of automatic
+    /// filtering.
+    pub fn classify_verdict(&self, msg: &str) -> Verdict {
+        let score = self.score(msg);
+        if score < self.ham_cutoff {
+            Verdict::Ham
+        } else if score > self.spam_cutoff {
+            Verdict::Spam
+        } else {
+            Verdict::Unsure
+        }
+    }
+
+    /// Compute the posterior probability of `msg` belonging to each category
+    /// the classifier knows about.
+    ///
+    /// The returned scores are normalized so they sum to `1.0` across every
+    /// trained category. Like `score_naive`, per-word ratings are combined
+    /// in log-space so long messages don't underflow every category's raw
+    /// product to `0.0`.
+    pub fn scores(&self, msg: &str) -> HashMap<String, f32> {
+        let log_scores: HashMap<String, f32> = self
+            .category_totals
+            .keys()
+            .map(|label| {
+                let ratings = self.rate_words(label, msg);
+                let log_product: f32 = ratings.iter().map(|p| p.ln()).sum();
+                (label.clone(), log_product)
+            })
+            .collect();
+
+        // Recover the normalized posteriors with a numerically stable
+        // log-sum-exp: subtract the max log-score before exponentiating.
+        let max_log_score = log_scores.values().cloned().fold(f32::NEG_INFINITY, f32::max);
+        if !max_log_score.is_finite() {
+            // No categories have been trained yet.
+            return log_scores.into_keys().map(|label| (label, 0.0)).collect();
+        }
+
+        let total: f32 = log_scores
+            .values()
+            .map(|log_score| (log_score - max_log_score).exp())
+            .sum();
+
+        log_scores
+            .into_iter()
+            .map(|(label, log_score)| (label, (log_score - max_log_score).exp() / total))
+            .collect()
+    }
+
+    /// Return the most probable category for `msg`.
+    ///
+    /// If the classifier has not been trained on any category yet, this
+    /// returns an empty string.
+    pub fn classify(&self, msg: &str) -> &str {
+        let scores = self.scores(msg);
+
+        self.category_totals
+            .keys()
+            .max_by(|a, b| {
+                let score_a = scores.get(a.as_str()).unwrap_or(&0.0);
+                let score_b = scores.get(b.as_str()).unwrap_or(&0.0);
+                score_a.partial_cmp(score_b).unwrap()
+            })
+            .map(|label| label.as_str())
+            .unwrap_or("")
+    }
+
+    /// Evaluate the classifier's spam/ham predictions against `test_set`, a
+    /// held-out collection of `(is_spam, msg)` pairs, and return a confusion
+    /// matrix together with accuracy, precision, recall and F1.
+    pub fn evaluate(&self, test_set: &[(bool, &str)]) -> Evaluation {
+        let mut evaluation = Evaluation::default();
+
+        for (is_spam, msg) in test_set {
+            match (*is_spam, self.identify(msg)) {
+                (true, true) => evaluation.true_positives += 1,
+                (true, false) => evaluation.false_negatives += 1,
+                (false, true) => evaluation.false_positives += 1,
+                (false, false) => evaluation.true_negatives += 1,
+            }
+        }
+
+        evaluation
     }
 }
 
-/// Compute the spam score of `msg`, based on a pre-trained model.
+/// Survival function of a chi-squared distribution with `2 * n` degrees of
+/// freedom.
+///
+/// For an even number of degrees of freedom this has the closed form
+/// `exp(-x / 2) * Σ_{k=0}^{n-1} (x / 2)^k / k!`. Evaluating the `(x / 2)^k`
+/// terms directly overflows `f32` for large `n` or `x` well before the
+/// `exp(-x / 2)` factor shrinks them back down, so the whole sum is instead
+/// accumulated in log-space and combined with a log-sum-exp, which stays
+/// accurate regardless of how large the individual terms get.
+fn chi2_survival(x: f32, n: usize) -> f32 {
+    if n == 0 {
+        return 1.0;
+    }
+
+    let half_x = x / 2.0;
+
+    // log_terms[k] = ln(term_k) = Σ_{j=1}^{k} ln(half_x / j), i.e. the log of
+    // the k-th term of the series, built via the same `term *= half_x / k`
+    // recurrence but in log-space so it can never overflow.
+    let mut log_terms = Vec::with_capacity(n);
+    let mut log_term = 0.0_f32;
+    log_terms.push(log_term);
+    for k in 1..n {
+        log_term += (half_x / (k as f32)).ln();
+        log_terms.push(log_term);
+    }
+
+    let max_log_term = log_terms.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let sum_exp: f32 = log_terms
+        .iter()
+        .map(|log_term| (log_term - max_log_term).exp())
+        .sum();
+    let log_sum = max_log_term + sum_exp.ln();
+
+    (-half_x + log_sum).exp().clamp(0.0, 1.0)
+}
+
+/// Compute the spam score of `msg`, based on the pre-trained model found at
+/// `model_path`.
+/// The higher the score, the stronger the liklihood that `msg` is a spam is.
+pub fn score_with_model(msg: &str, model_path: impl AsRef<Path>) -> Result<f32, io::Error> {
+    let file = File::open(model_path)?;
+    Classifier::new_from_pre_trained(file).map(|classifier| classifier.score(msg))
+}
+
+/// Compute the spam score of `msg`, based on the pre-trained model found at
+/// `model.json` in the current working directory.
 /// The higher the score, the stronger the liklihood that `msg` is a spam is.
 pub fn score(msg: &str) -> Result<f32, io::Error> {
-    let mut file = File::open(DEFAULT_FILE_PATH)?;
-    Classifier::new_from_pre_trained(&mut file).map(|classifier| classifier.score(msg))
+    score_with_model(msg, DEFAULT_FILE_PATH)
+}
+
+/// Identify whether `msg` is a spam or not, based on the pre-trained model
+/// found at `model_path`.
+pub fn identify_with_model(msg: &str, model_path: impl AsRef<Path>) -> Result<bool, io::Error> {
+    let score = score_with_model(msg, model_path)?;
+    Ok(score > SPAM_PROB_THRESHOLD)
 }
 
-/// Identify whether `msg` is a spam or not, based on a pre-trained model.
+/// Identify whether `msg` is a spam or not, based on the pre-trained model
+/// found at `model.json` in the current working directory.
 pub fn identify(msg: &str) -> Result<bool, io::Error> {
-    let score = score(msg)?;
-    let is_spam = score > SPAM_PROB_THRESHOLD;
-    Ok(is_spam)
+    identify_with_model(msg, DEFAULT_FILE_PATH)
 }
 
 #[cfg(test)]
@@ -248,4 +795,314 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_score_chi2() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        // Train the classifier with a new spam example
+        let spam = "Don't forget our special promotion: -30% on men shoes, only today!";
+        classifier.train_spam(spam);
+
+        // Train the classifier with a new ham example
+        let ham = "Hi Bob, don't forget our meeting today at 4pm.";
+        classifier.train_ham(ham);
+
+        // Identify a typical spam message
+        let spam = "Lose up to 19% weight. Special promotion on our new weightloss.";
+        assert!(classifier.score_chi2(spam) > classifier.score_chi2(ham));
+    }
+
+    #[test]
+    fn test_score_long_message_without_truncation() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+        classifier.set_truncate_long_messages(false);
+
+        // Train the classifier with a new spam example
+        let spam = "Don't forget our special promotion: -30% on men shoes, only today!";
+        classifier.train_spam(spam);
+
+        // Train the classifier with a new ham example
+        let ham = "Hi Bob, don't forget our meeting today at 4pm.";
+        classifier.train_ham(ham);
+
+        // A long spam message, well over the historical 20-word cap
+        let long_spam = "Lose up to 19% weight. Special promotion on our new weightloss. "
+            .repeat(5);
+        assert!(classifier.identify(&long_spam));
+    }
+
+    #[test]
+    fn test_smoothing_scales_with_evidence() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        // A word seen only once in spam, and a word with lots of
+        // corroborating spam evidence
+        classifier.train_spam("cheap");
+        for _ in 0..50 {
+            classifier.train_spam("urgent");
+        }
+        classifier.train_ham("meeting schedule agenda notes");
+
+        // A word with little evidence should rate closer to the prior than a
+        // word that has been seen many times in the same category
+        let rare_score = classifier.score("cheap");
+        let frequent_score = classifier.score("urgent");
+        assert!(frequent_score > rare_score);
+    }
+
+    #[test]
+    fn test_save_and_load_in_memory() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        classifier.train_spam("Lose up to 19% weight. Special promotion on our new weightloss.");
+        classifier.train_ham("Hi Bob, can you send me your machine learning homework?");
+
+        // Round-trip the model through an in-memory buffer instead of a file
+        let mut buffer = Vec::new();
+        classifier.save(&mut buffer, false).unwrap();
+        let reloaded = Classifier::new_from_pre_trained(buffer.as_slice()).unwrap();
+
+        let spam = "Lose up to 19% weight. Special promotion on our new weightloss.";
+        assert_eq!(classifier.identify(spam), reloaded.identify(spam));
+    }
+
+    #[test]
+    #[cfg(feature = "bincode")]
+    fn test_save_and_load_bincode() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        classifier.train_spam("Lose up to 19% weight. Special promotion on our new weightloss.");
+        classifier.train_ham("Hi Bob, can you send me your machine learning homework?");
+
+        // Round-trip the model through the compact bincode format
+        let mut buffer = Vec::new();
+        classifier.save_bincode(&mut buffer).unwrap();
+        let reloaded = Classifier::new_from_pre_trained_bincode(buffer.as_slice()).unwrap();
+
+        let spam = "Lose up to 19% weight. Special promotion on our new weightloss.";
+        assert_eq!(classifier.identify(spam), reloaded.identify(spam));
+    }
+
+    #[test]
+    fn test_tokenizer_lowercase_merges_case_variants() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_lowercase(true);
+        classifier.set_tokenizer(tokenizer);
+
+        classifier.train_spam("FREE FREE FREE");
+        classifier.train_ham("Hi Bob, how are you?");
+
+        // "free" and "FREE" must be counted as the same token
+        assert!(classifier.identify("free money, click now"));
+    }
+
+    #[test]
+    fn test_tokenizer_stop_words_match_regardless_of_case() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_lowercase(true);
+        tokenizer.set_stop_words(HashSet::from(["The".to_string()]));
+
+        // "The" was supplied in natural case, but lowercasing is enabled, so
+        // every case variant of the word must still be dropped
+        let tokens = tokenizer.tokenize("The free offer is the best offer");
+        assert!(!tokens.contains(&"the".to_string()));
+        assert!(tokens.contains(&"free".to_string()));
+    }
+
+    #[test]
+    fn test_tokenizer_ngrams_capture_phrases() {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.set_ngram_range(1, 2);
+
+        let tokens = tokenizer.tokenize("free money");
+        assert!(tokens.contains(&"free".to_string()));
+        assert!(tokens.contains(&"money".to_string()));
+        assert!(tokens.contains(&"free money".to_string()));
+    }
+
+    #[test]
+    fn test_classify_verdict() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        classifier.train_spam("Lose up to 19% weight. Special promotion on our new weightloss.");
+        classifier.train_ham("Hi Bob, can you send me your machine learning homework?");
+
+        let spam = "Lose up to 19% weight. Special promotion on our new weightloss.";
+        assert_eq!(classifier.classify_verdict(spam), Verdict::Spam);
+
+        let ham = "Hi Bob, can you send me your machine learning homework?";
+        assert_eq!(classifier.classify_verdict(ham), Verdict::Ham);
+
+        // A never-trained word falls squarely on the prior and should land
+        // in the unsure band rather than being forced into ham or spam
+        assert_eq!(classifier.classify_verdict("xyzzy"), Verdict::Unsure);
+    }
+
+    #[test]
+    fn test_identify_agrees_with_classify_verdict_cutoffs() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        classifier.train_spam("Lose up to 19% weight. Special promotion on our new weightloss.");
+        classifier.train_ham("Hi Bob, can you send me your machine learning homework?");
+        classifier.set_verdict_cutoffs(0.2, 0.4);
+
+        // A never-trained word scores at the prior (0.5), which is now above
+        // the spam cutoff: classify_verdict calls this Spam, so identify must too
+        let msg = "xyzzy";
+        assert_eq!(classifier.classify_verdict(msg), Verdict::Spam);
+        assert!(classifier.identify(msg));
+    }
+
+    #[test]
+    fn test_untrain() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        // Train, then fully reverse the training of the same message
+        classifier.train_spam("free money now");
+        classifier.untrain_spam("free money now");
+
+        // The message should score the same as it would on a classifier
+        // that was never trained with it
+        let fresh = Classifier::new();
+        assert_eq!(classifier.score("free money now"), fresh.score("free money now"));
+    }
+
+    #[test]
+    fn test_untrain_corrects_a_misfiled_message() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        let spam = "Don't forget our special promotion: -30% on men shoes, only today!";
+        classifier.train_spam(spam);
+
+        // This ham message was mistakenly trained as spam
+        let misfiled = "Hi Bob, don't forget our meeting today at 4pm.";
+        classifier.train_spam(misfiled);
+        classifier.untrain_spam(misfiled);
+        classifier.train_ham(misfiled);
+
+        let is_spam = classifier.identify(misfiled);
+        assert!(!is_spam);
+    }
+
+    #[test]
+    fn test_untrain_drops_a_fully_reversed_category() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        classifier.train("sport", "The team won the match after a great final goal.");
+        classifier.train("finance", "The stock market rallied after the interest rate cut.");
+        classifier.untrain("sport", "The team won the match after a great final goal.");
+
+        // "sport" has been fully untrained and must no longer show up as a
+        // live category among the scores or as a possible classification
+        let scores = classifier.scores("Heavy rain is expected this afternoon.");
+        assert!(!scores.contains_key("sport"));
+        assert_ne!(classifier.classify("The market and the stock rallied on rate news."), "sport");
+    }
+
+    #[test]
+    fn test_train_from_dir() -> Result<(), io::Error> {
+        let dir = std::env::temp_dir().join("bayespam_test_train_from_dir");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        fs::write(
+            dir.join("a.txt"),
+            "Lose up to 19% weight. Special promotion on our new weightloss.",
+        )?;
+        fs::write(
+            dir.join("b.txt"),
+            "Amazing weightloss results with our new promotion, act now!",
+        )?;
+
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+        classifier.train_from_dir(&dir, SPAM_LABEL)?;
+        classifier.train_ham("Hi Bob, can you send me your machine learning homework?");
+
+        let spam = "Amazing new weightloss promotion, act now!";
+        assert!(classifier.identify(spam));
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        classifier.train_spam("Lose up to 19% weight. Special promotion on our new weightloss.");
+        classifier.train_ham("Hi Bob, can you send me your machine learning homework?");
+
+        let test_set = [
+            (true, "Special promotion: lose weight now with our weightloss plan."),
+            (false, "Hi Bob, can you send me your homework from class?"),
+        ];
+        let evaluation = classifier.evaluate(&test_set);
+
+        assert_eq!(evaluation.true_positives, 1);
+        assert_eq!(evaluation.true_negatives, 1);
+        assert_eq!(evaluation.false_positives, 0);
+        assert_eq!(evaluation.false_negatives, 0);
+        assert_eq!(evaluation.accuracy(), 1.0);
+        assert_eq!(evaluation.precision(), 1.0);
+        assert_eq!(evaluation.recall(), 1.0);
+        assert_eq!(evaluation.f1(), 1.0);
+    }
+
+    #[test]
+    fn test_multi_category() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        classifier.train("sport", "The team won the match after a great final goal.");
+        classifier.train("finance", "The stock market rallied after the interest rate cut.");
+        classifier.train("weather", "Expect heavy rain and strong wind this afternoon.");
+
+        let category = classifier.classify("The market and the stock rallied on rate news.");
+        assert_eq!(category, "finance");
+
+        let scores = classifier.scores("Heavy rain is expected this afternoon.");
+        assert!(scores.contains_key("sport"));
+        assert!(scores.contains_key("finance"));
+        assert!(scores.contains_key("weather"));
+    }
+
+    #[test]
+    fn test_scores_does_not_underflow_on_long_messages() {
+        // Create a new classifier with an empty model
+        let mut classifier = Classifier::new();
+
+        classifier.train("sport", "The team won the match after a great final goal.");
+        classifier.train("finance", "The stock market rallied after the interest rate cut.");
+        classifier.train("weather", "Expect heavy rain and strong wind this afternoon.");
+
+        // A long message made of words none of the three categories have
+        // ever seen; the raw per-category products would all underflow to
+        // 0.0 without a log-space combiner
+        let long_unseen_message = (0..300)
+            .map(|i| format!("unseenword{}", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let scores = classifier.scores(&long_unseen_message);
+
+        let total: f32 = scores.values().sum();
+        assert!((total - 1.0).abs() < 1e-3);
+        for score in scores.values() {
+            assert!(score.is_finite());
+        }
+    }
 }